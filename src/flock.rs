@@ -0,0 +1,174 @@
+//! A higher-level filesystem abstraction that pairs a root path with
+//! coordinated file locking, modeled on cargo's `flock` module.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Result, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{lock_contended_error, FileExt};
+
+/// A file opened and locked through a [`Filesystem`].
+///
+/// `FileLock` implements `Read`, `Write` and `Seek` by delegating to the
+/// file it holds open, and releases its lock when dropped.
+pub struct FileLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl FileLock {
+    /// Returns the underlying file.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Returns the path of the locked file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the parent directory of the locked file.
+    pub fn parent(&self) -> &Path {
+        self.path
+            .parent()
+            .expect("locked file path should have a parent")
+    }
+}
+
+impl Read for FileLock {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Write for FileLock {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for FileLock {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+/// A directory within which files can be opened under a coordinated lock.
+///
+/// Opening a file through [`open_ro`](Filesystem::open_ro) or
+/// [`open_rw`](Filesystem::open_rw) creates any missing parent directories,
+/// opens the file with the appropriate permissions, and locks it, returning
+/// a [`FileLock`] that releases the lock when dropped. This lets multiple
+/// processes coordinate access to files under the same root through the
+/// operating system's file locks rather than in-process synchronization.
+pub struct Filesystem {
+    root: PathBuf,
+}
+
+impl Filesystem {
+    /// Creates a new `Filesystem` rooted at `root`.
+    ///
+    /// This does not create `root` on disk; directories are created lazily
+    /// as files beneath them are opened.
+    pub fn new(root: PathBuf) -> Filesystem {
+        Filesystem { root }
+    }
+
+    /// Returns the root path of this filesystem.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Opens `path`, relative to the root, for reading, and blocks until a
+    /// shared lock on it can be acquired.
+    ///
+    /// If the lock is already held exclusively by another process, `msg` is
+    /// passed to `on_contended` before blocking on the lock, so that callers
+    /// can report that they're waiting (e.g. "waiting for file lock on
+    /// ...").
+    pub fn open_ro<P, F>(&self, path: P, on_contended: F, msg: &str) -> Result<FileLock>
+    where
+        P: AsRef<Path>,
+        F: Fn(&str),
+    {
+        self.open(
+            path.as_ref(),
+            OpenOptions::new().read(true),
+            false,
+            on_contended,
+            msg,
+        )
+    }
+
+    /// Opens `path`, relative to the root, for reading and writing, creating
+    /// it and any missing parent directories if necessary, and blocks until
+    /// an exclusive lock on it can be acquired.
+    ///
+    /// If the lock is already held by another process, `msg` is passed to
+    /// `on_contended` before blocking on the lock, so that callers can
+    /// report that they're waiting (e.g. "waiting for file lock on ...").
+    pub fn open_rw<P, F>(&self, path: P, on_contended: F, msg: &str) -> Result<FileLock>
+    where
+        P: AsRef<Path>,
+        F: Fn(&str),
+    {
+        self.open(
+            path.as_ref(),
+            OpenOptions::new().read(true).write(true).create(true),
+            true,
+            on_contended,
+            msg,
+        )
+    }
+
+    fn open<F>(
+        &self,
+        path: &Path,
+        opts: &OpenOptions,
+        exclusive: bool,
+        on_contended: F,
+        msg: &str,
+    ) -> Result<FileLock>
+    where
+        F: Fn(&str),
+    {
+        let path = self.root.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = opts.open(&path)?;
+
+        // Fully qualified: `File::try_lock_shared` is also an inherent method
+        // as of Rust 1.89, returning `Result<(), TryLockError>` rather than
+        // this crate's `io::Result<()>`.
+        let try_lock_result = if exclusive {
+            FileExt::try_lock_exclusive(&file)
+        } else {
+            FileExt::try_lock_shared(&file)
+        };
+
+        if let Err(err) = try_lock_result {
+            if err.kind() != lock_contended_error().kind() {
+                return Err(err);
+            }
+            on_contended(msg);
+            if exclusive {
+                FileExt::lock_exclusive(&file)?;
+            } else {
+                FileExt::lock_shared(&file)?;
+            }
+        }
+
+        Ok(FileLock { file, path })
+    }
+}
@@ -1,3 +1,8 @@
+// These tests open fresh files under a per-test `tempdir`, so there's never
+// anything to truncate, and `statvfs`-family functions read more naturally
+// taking `&Path` at call sites than `Path`.
+#![allow(clippy::suspicious_open_options, clippy::needless_borrows_for_generic_args)]
+
 extern crate tempdir;
 
 use crate::*;
@@ -107,8 +112,11 @@ fn lock_exclusive() {
         file2.try_lock_exclusive().unwrap_err().kind(),
         lock_contended_error().kind()
     );
+    // Fully qualified: `File::try_lock_shared` is also an inherent method
+    // as of Rust 1.89, returning `Result<(), TryLockError>` rather than
+    // this crate's `io::Result<()>`.
     assert_eq!(
-        file2.try_lock_shared().unwrap_err().kind(),
+        FileExt::try_lock_shared(&file2).unwrap_err().kind(),
         lock_contended_error().kind()
     );
 
@@ -136,7 +144,7 @@ fn lock_cleanup() {
 
     file1.lock_exclusive().unwrap();
     assert_eq!(
-        file2.try_lock_shared().unwrap_err().kind(),
+        FileExt::try_lock_shared(&file2).unwrap_err().kind(),
         lock_contended_error().kind()
     );
 
@@ -145,6 +153,217 @@ fn lock_cleanup() {
     file2.lock_shared().unwrap();
 }
 
+/// Tests locking and unlocking disjoint byte ranges of a file.
+///
+/// Note that this doesn't test cross-handle contention within a single
+/// process: unlike the whole-file `flock`-based locks tested above, POSIX
+/// `fcntl` record locks are owned per-process, so two handles opened by the
+/// same process never contend with each other.
+#[test]
+fn lock_range() {
+    let (_dir, path) = tmpfile();
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&path)
+        .unwrap();
+
+    file.lock_exclusive_range(0, 10).unwrap();
+    file.lock_shared_range(10, 10).unwrap();
+    file.unlock_range(0, 10).unwrap();
+    file.unlock_range(10, 10).unwrap();
+}
+
+/// Tests that a timed lock gives up once its deadline elapses.
+#[test]
+fn lock_exclusive_timeout_expires() {
+    let (_dir, path) = tmpfile();
+    let file1 = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&path)
+        .unwrap();
+    let file2 = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&path)
+        .unwrap();
+
+    file1.lock_exclusive().unwrap();
+    assert_eq!(
+        file2
+            .lock_exclusive_timeout(std::time::Duration::from_millis(50))
+            .unwrap_err()
+            .kind(),
+        lock_contended_error().kind()
+    );
+}
+
+/// Tests that a timed lock succeeds once the contending lock is released
+/// before the deadline.
+#[test]
+fn lock_exclusive_timeout_succeeds() {
+    let (_dir, path) = tmpfile();
+    let file1 = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&path)
+        .unwrap();
+    let file2 = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&path)
+        .unwrap();
+
+    file1.lock_exclusive().unwrap();
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            file1.unlock().unwrap();
+        });
+        file2
+            .lock_exclusive_timeout(std::time::Duration::from_secs(1))
+            .unwrap();
+    });
+}
+
+/// Tests that the `RawFileExt` locking methods, generic over any
+/// descriptor/handle-like type, work the same as the `File`-specific ones.
+#[test]
+fn raw_file_ext() {
+    let (_dir, path) = tmpfile();
+    let file1 = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&path)
+        .unwrap();
+    let file2 = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&path)
+        .unwrap();
+
+    file1.lock_exclusive_raw().unwrap();
+    assert_eq!(
+        file2.try_lock_shared_raw().unwrap_err().kind(),
+        lock_contended_error().kind()
+    );
+    file1.unlock_raw().unwrap();
+    file2.lock_shared_raw().unwrap();
+}
+
+/// Tests that lock guards release their lock when dropped.
+#[test]
+fn lock_guard() {
+    let (_dir, path) = tmpfile();
+    let mut file1 = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&path)
+        .unwrap();
+    let file2 = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&path)
+        .unwrap();
+
+    {
+        let mut guard = file1.lock_exclusive_guard().unwrap();
+        assert_eq!(
+            FileExt::try_lock_shared(&file2).unwrap_err().kind(),
+            lock_contended_error().kind()
+        );
+        guard.write_all(b"foo").unwrap();
+    }
+
+    // The exclusive lock is released once the guard is dropped.
+    file2.lock_shared().unwrap();
+}
+
+/// Tests that the lock held by an exclusive guard is seen, and once the
+/// guard is dropped is free, from a second handle locked only through a
+/// `&dyn FileExt` trait object, which can only reach this crate's methods.
+///
+/// This checks the guard's lock state, not which methods the guard itself
+/// calls: `File`'s own inherent `lock_exclusive`/`unlock` (stable since Rust
+/// 1.89) take the same kind of lock on Unix, so a guard calling them would
+/// pass this test too.
+#[test]
+fn lock_guard_dispatches_through_file_ext() {
+    let (_dir, path) = tmpfile();
+    let mut file1 = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&path)
+        .unwrap();
+    let file2 = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(&path)
+        .unwrap();
+    let file2_ext: &dyn FileExt = &file2;
+
+    let guard = file1.lock_exclusive_guard().unwrap();
+    assert_eq!(
+        file2_ext.try_lock_exclusive().unwrap_err().kind(),
+        lock_contended_error().kind()
+    );
+    drop(guard);
+    file2_ext.lock_exclusive().unwrap();
+}
+
+/// Tests that `Filesystem` creates missing parent directories and locks
+/// opened files.
+#[test]
+fn filesystem_open_rw() {
+    let dir = tmpdir();
+    let filesystem = Filesystem::new(dir.path().to_owned());
+
+    let mut file = filesystem
+        .open_rw("nested/dir/file", |_| panic!("lock should not contend"), "")
+        .unwrap();
+    assert_eq!(file.path(), dir.path().join("nested/dir/file"));
+    file.write_all(b"foo").unwrap();
+}
+
+/// Tests that `Filesystem::open_ro` reports contention before blocking.
+#[test]
+fn filesystem_open_ro_contended() {
+    let (_dir, path) = tmpfile();
+    let filesystem = Filesystem::new(path.parent().unwrap().to_owned());
+    let name = path.file_name().unwrap();
+
+    let writer = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .open(&path)
+        .unwrap();
+    writer.lock_exclusive().unwrap();
+
+    let contended = std::cell::Cell::new(false);
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            writer.unlock().unwrap();
+        });
+        filesystem
+            .open_ro(name, |_| contended.set(true), "waiting for lock")
+            .unwrap();
+    });
+    assert!(contended.get());
+}
+
 /// Tests file allocation.
 #[test]
 fn allocate() {
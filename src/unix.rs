@@ -0,0 +1,219 @@
+extern crate libc;
+
+use std::fs::File;
+use std::io::{Error, Result};
+use std::mem;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, OwnedFd};
+use std::path::Path;
+
+use self::libc::{c_int, flock as c_flock, off_t};
+
+use crate::FsStats;
+
+pub fn duplicate(file: &File) -> Result<File> {
+    dup(file.as_fd()).map(File::from)
+}
+
+#[cfg(feature = "rustix")]
+fn dup(fd: BorrowedFd<'_>) -> Result<OwnedFd> {
+    rustix::io::dup(fd).map_err(Error::from)
+}
+
+#[cfg(not(feature = "rustix"))]
+fn dup(fd: BorrowedFd<'_>) -> Result<OwnedFd> {
+    // SAFETY: `dup` returns a new, independently-owned descriptor, which we
+    // immediately hand to `OwnedFd` so there is no raw descriptor juggling
+    // beyond this call.
+    unsafe {
+        let raw = libc::dup(fd.as_raw_fd());
+        if raw < 0 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(std::os::unix::io::FromRawFd::from_raw_fd(raw))
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn allocated_size(file: &File) -> Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+    file.metadata().map(|m| m.blocks() * 512)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn allocated_size(file: &File) -> Result<u64> {
+    file.metadata().map(|m| m.len())
+}
+
+#[cfg(target_os = "linux")]
+pub fn allocate(file: &File, len: u64) -> Result<()> {
+    let ret = unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, len as off_t) };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(Error::from_raw_os_error(ret))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn allocate(file: &File, len: u64) -> Result<()> {
+    if file.metadata()?.len() < len {
+        file.set_len(len)
+    } else {
+        Ok(())
+    }
+}
+
+pub fn lock_shared(file: &File) -> Result<()> {
+    lock_shared_fd(file.as_fd())
+}
+
+pub fn lock_exclusive(file: &File) -> Result<()> {
+    lock_exclusive_fd(file.as_fd())
+}
+
+pub fn try_lock_shared(file: &File) -> Result<()> {
+    try_lock_shared_fd(file.as_fd())
+}
+
+pub fn try_lock_exclusive(file: &File) -> Result<()> {
+    try_lock_exclusive_fd(file.as_fd())
+}
+
+pub fn unlock(file: &File) -> Result<()> {
+    unlock_fd(file.as_fd())
+}
+
+pub fn lock_shared_fd(fd: BorrowedFd<'_>) -> Result<()> {
+    flock(fd, libc::LOCK_SH)
+}
+
+pub fn lock_exclusive_fd(fd: BorrowedFd<'_>) -> Result<()> {
+    flock(fd, libc::LOCK_EX)
+}
+
+pub fn try_lock_shared_fd(fd: BorrowedFd<'_>) -> Result<()> {
+    flock(fd, libc::LOCK_SH | libc::LOCK_NB)
+}
+
+pub fn try_lock_exclusive_fd(fd: BorrowedFd<'_>) -> Result<()> {
+    flock(fd, libc::LOCK_EX | libc::LOCK_NB)
+}
+
+pub fn unlock_fd(fd: BorrowedFd<'_>) -> Result<()> {
+    flock(fd, libc::LOCK_UN)
+}
+
+pub fn lock_error() -> Error {
+    Error::from_raw_os_error(libc::EWOULDBLOCK)
+}
+
+/// Whole-file locking via `flock(2)`.
+///
+/// Solaris (and other SVR4-derived systems) has no `flock(2)`; fall back to
+/// the equivalent whole-file `fcntl(2)` record lock there.
+#[cfg(all(not(target_os = "solaris"), not(feature = "rustix")))]
+fn flock(fd: BorrowedFd<'_>, flag: c_int) -> Result<()> {
+    let ret = unsafe { libc::flock(fd.as_raw_fd(), flag) };
+    if ret < 0 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(all(not(target_os = "solaris"), feature = "rustix"))]
+fn flock(fd: BorrowedFd<'_>, flag: c_int) -> Result<()> {
+    let nonblocking = flag & libc::LOCK_NB != 0;
+    let operation = match (flag & !libc::LOCK_NB, nonblocking) {
+        (libc::LOCK_SH, false) => rustix::fs::FlockOperation::LockShared,
+        (libc::LOCK_SH, true) => rustix::fs::FlockOperation::NonBlockingLockShared,
+        (libc::LOCK_EX, false) => rustix::fs::FlockOperation::LockExclusive,
+        (libc::LOCK_EX, true) => rustix::fs::FlockOperation::NonBlockingLockExclusive,
+        (libc::LOCK_UN, _) => rustix::fs::FlockOperation::Unlock,
+        _ => unreachable!("unexpected flock() flag combination"),
+    };
+    rustix::fs::flock(fd, operation).map_err(Error::from)
+}
+
+#[cfg(target_os = "solaris")]
+fn flock(fd: BorrowedFd<'_>, flag: c_int) -> Result<()> {
+    let (lock_type, blocking) = match flag & !libc::LOCK_NB {
+        libc::LOCK_SH => (libc::F_RDLCK, flag & libc::LOCK_NB == 0),
+        libc::LOCK_EX => (libc::F_WRLCK, flag & libc::LOCK_NB == 0),
+        libc::LOCK_UN => (libc::F_UNLCK, true),
+        _ => unreachable!(),
+    };
+    fcntl_lock(fd, lock_type, blocking, 0, 0)
+}
+
+/// Locks `[offset, offset + len)` of `fd` using `fcntl(2)` `F_SETLK`
+/// (non-blocking) or `F_SETLKW` (blocking) record locks. `len == 0` means
+/// "to the end of the file", matching POSIX `fcntl` semantics.
+///
+/// Note that unlike the whole-file `flock(2)` locks used elsewhere in this
+/// module, POSIX record locks are owned per-process (not per file
+/// descriptor) and are released as soon as *any* descriptor referring to the
+/// file is closed, even one the caller didn't use to take the lock. Don't
+/// mix region locks and whole-file locks on the same file.
+///
+/// This always goes through raw `libc::fcntl`, even when the `rustix`
+/// feature is enabled: `rustix::fs::fcntl_lock` only wraps the whole-file
+/// `F_RDLCK`/`F_WRLCK`/`F_UNLCK` case (`l_start`/`l_len` fixed at `0`/`0`),
+/// with no byte-range equivalent, so there's no rustix API to route this
+/// through.
+fn fcntl_lock(fd: BorrowedFd<'_>, lock_type: c_int, blocking: bool, offset: u64, len: u64) -> Result<()> {
+    let mut flock: c_flock = unsafe { mem::zeroed() };
+    flock.l_type = lock_type as _;
+    flock.l_whence = libc::SEEK_SET as _;
+    flock.l_start = offset as off_t;
+    flock.l_len = len as off_t;
+
+    let cmd = if blocking { libc::F_SETLKW } else { libc::F_SETLK };
+    let ret = unsafe { libc::fcntl(fd.as_raw_fd(), cmd, &flock) };
+    if ret < 0 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+pub fn lock_shared_range(file: &File, offset: u64, len: u64) -> Result<()> {
+    fcntl_lock(file.as_fd(), libc::F_RDLCK, true, offset, len)
+}
+
+pub fn lock_exclusive_range(file: &File, offset: u64, len: u64) -> Result<()> {
+    fcntl_lock(file.as_fd(), libc::F_WRLCK, true, offset, len)
+}
+
+pub fn try_lock_shared_range(file: &File, offset: u64, len: u64) -> Result<()> {
+    fcntl_lock(file.as_fd(), libc::F_RDLCK, false, offset, len)
+}
+
+pub fn try_lock_exclusive_range(file: &File, offset: u64, len: u64) -> Result<()> {
+    fcntl_lock(file.as_fd(), libc::F_WRLCK, false, offset, len)
+}
+
+pub fn unlock_range(file: &File, offset: u64, len: u64) -> Result<()> {
+    fcntl_lock(file.as_fd(), libc::F_UNLCK, true, offset, len)
+}
+
+pub fn statvfs(path: &Path) -> Result<FsStats> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    unsafe {
+        let mut stat: libc::statvfs = mem::zeroed();
+        if libc::statvfs(path.as_ptr(), &mut stat) < 0 {
+            return Err(Error::last_os_error());
+        }
+        let bsize = stat.f_frsize as u64;
+        Ok(FsStats {
+            free_space: bsize * stat.f_bfree as u64,
+            available_space: bsize * stat.f_bavail as u64,
+            total_space: bsize * stat.f_blocks as u64,
+            allocation_granularity: bsize,
+        })
+    }
+}
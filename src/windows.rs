@@ -0,0 +1,228 @@
+extern crate winapi;
+
+use std::fs::File;
+use std::io::{Error, Result};
+use std::mem;
+use std::os::windows::ffi::OsStrExt;
+use std::os::windows::io::{AsHandle, AsRawHandle, BorrowedHandle, OwnedHandle};
+use std::path::Path;
+
+use self::winapi::shared::minwindef::DWORD;
+use self::winapi::shared::winerror::ERROR_LOCK_VIOLATION;
+use self::winapi::um::fileapi::{GetDiskFreeSpaceExW, GetDiskFreeSpaceW, LockFileEx, UnlockFileEx};
+use self::winapi::um::handleapi::DuplicateHandle;
+use self::winapi::um::minwinbase::{LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY, OVERLAPPED};
+use self::winapi::um::processthreadsapi::GetCurrentProcess;
+use self::winapi::um::winnt::DUPLICATE_SAME_ACCESS;
+
+use crate::FsStats;
+
+pub fn duplicate(file: &File) -> Result<File> {
+    dup(file.as_handle()).map(File::from)
+}
+
+fn dup(handle: BorrowedHandle<'_>) -> Result<OwnedHandle> {
+    // SAFETY: `DuplicateHandle` returns a new, independently-owned handle,
+    // which we immediately hand to `OwnedHandle` so there is no raw handle
+    // juggling beyond this call.
+    unsafe {
+        let process = GetCurrentProcess();
+        let mut raw = mem::zeroed();
+        let ret = DuplicateHandle(
+            process,
+            handle.as_raw_handle(),
+            process,
+            &mut raw,
+            0,
+            true as DWORD,
+            DUPLICATE_SAME_ACCESS,
+        );
+        if ret == 0 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(std::os::windows::io::FromRawHandle::from_raw_handle(raw))
+        }
+    }
+}
+
+pub fn allocated_size(file: &File) -> Result<u64> {
+    file.metadata().map(|m| m.len())
+}
+
+pub fn allocate(file: &File, len: u64) -> Result<()> {
+    if file.metadata()?.len() < len {
+        file.set_len(len)
+    } else {
+        Ok(())
+    }
+}
+
+pub fn lock_shared(file: &File) -> Result<()> {
+    lock_shared_handle(file.as_handle())
+}
+
+pub fn lock_exclusive(file: &File) -> Result<()> {
+    lock_exclusive_handle(file.as_handle())
+}
+
+pub fn try_lock_shared(file: &File) -> Result<()> {
+    try_lock_shared_handle(file.as_handle())
+}
+
+pub fn try_lock_exclusive(file: &File) -> Result<()> {
+    try_lock_exclusive_handle(file.as_handle())
+}
+
+pub fn unlock(file: &File) -> Result<()> {
+    unlock_handle(file.as_handle())
+}
+
+pub fn lock_shared_handle(handle: BorrowedHandle<'_>) -> Result<()> {
+    lock_file_range(handle, 0, 0, !0u64)
+}
+
+pub fn lock_exclusive_handle(handle: BorrowedHandle<'_>) -> Result<()> {
+    lock_file_range(handle, LOCKFILE_EXCLUSIVE_LOCK, 0, !0u64)
+}
+
+pub fn try_lock_shared_handle(handle: BorrowedHandle<'_>) -> Result<()> {
+    lock_file_range(handle, LOCKFILE_FAIL_IMMEDIATELY, 0, !0u64)
+}
+
+pub fn try_lock_exclusive_handle(handle: BorrowedHandle<'_>) -> Result<()> {
+    lock_file_range(
+        handle,
+        LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY,
+        0,
+        !0u64,
+    )
+}
+
+pub fn unlock_handle(handle: BorrowedHandle<'_>) -> Result<()> {
+    unlock_file_range(handle, 0, !0u64)
+}
+
+pub fn lock_error() -> Error {
+    Error::from_raw_os_error(ERROR_LOCK_VIOLATION as i32)
+}
+
+/// Locks `[offset, offset + len)` of `file` via `LockFileEx`, with the
+/// offset and length split across the high/low halves of the `OVERLAPPED`
+/// structure and the `nNumberOfBytesToLock{Low,High}` arguments
+/// respectively. `flags` carries `LOCKFILE_EXCLUSIVE_LOCK` and
+/// `LOCKFILE_FAIL_IMMEDIATELY` as appropriate.
+fn lock_file_range(handle: BorrowedHandle<'_>, flags: DWORD, offset: u64, len: u64) -> Result<()> {
+    unsafe {
+        let mut overlapped: OVERLAPPED = mem::zeroed();
+        *overlapped.u.s_mut() = {
+            let mut s: winapi::um::minwinbase::OVERLAPPED_u_s = mem::zeroed();
+            s.Offset = offset as u32;
+            s.OffsetHigh = (offset >> 32) as u32;
+            s
+        };
+        let ret = LockFileEx(
+            handle.as_raw_handle(),
+            flags,
+            0,
+            len as u32,
+            (len >> 32) as u32,
+            &mut overlapped,
+        );
+        if ret == 0 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn unlock_file_range(handle: BorrowedHandle<'_>, offset: u64, len: u64) -> Result<()> {
+    unsafe {
+        let mut overlapped: OVERLAPPED = mem::zeroed();
+        *overlapped.u.s_mut() = {
+            let mut s: winapi::um::minwinbase::OVERLAPPED_u_s = mem::zeroed();
+            s.Offset = offset as u32;
+            s.OffsetHigh = (offset >> 32) as u32;
+            s
+        };
+        let ret = UnlockFileEx(
+            handle.as_raw_handle(),
+            0,
+            len as u32,
+            (len >> 32) as u32,
+            &mut overlapped,
+        );
+        if ret == 0 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub fn lock_shared_range(file: &File, offset: u64, len: u64) -> Result<()> {
+    lock_file_range(file.as_handle(), 0, offset, len)
+}
+
+pub fn lock_exclusive_range(file: &File, offset: u64, len: u64) -> Result<()> {
+    lock_file_range(file.as_handle(), LOCKFILE_EXCLUSIVE_LOCK, offset, len)
+}
+
+pub fn try_lock_shared_range(file: &File, offset: u64, len: u64) -> Result<()> {
+    lock_file_range(file.as_handle(), LOCKFILE_FAIL_IMMEDIATELY, offset, len)
+}
+
+pub fn try_lock_exclusive_range(file: &File, offset: u64, len: u64) -> Result<()> {
+    lock_file_range(
+        file.as_handle(),
+        LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY,
+        offset,
+        len,
+    )
+}
+
+pub fn unlock_range(file: &File, offset: u64, len: u64) -> Result<()> {
+    unlock_file_range(file.as_handle(), offset, len)
+}
+
+pub fn statvfs(path: &Path) -> Result<FsStats> {
+    let mut wide: Vec<u16> = path.as_os_str().encode_wide().collect();
+    wide.push(0);
+
+    unsafe {
+        let mut free_space = 0u64;
+        let mut total_space = 0u64;
+        let mut available_space = 0u64;
+        let ret = GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut available_space,
+            &mut total_space,
+            &mut free_space,
+        );
+        if ret == 0 {
+            return Err(Error::last_os_error());
+        }
+
+        let mut sectors_per_cluster = 0;
+        let mut bytes_per_sector = 0;
+        let mut free_clusters = 0;
+        let mut total_clusters = 0;
+        let ret = GetDiskFreeSpaceW(
+            wide.as_ptr(),
+            &mut sectors_per_cluster,
+            &mut bytes_per_sector,
+            &mut free_clusters,
+            &mut total_clusters,
+        );
+        if ret == 0 {
+            return Err(Error::last_os_error());
+        }
+
+        Ok(FsStats {
+            free_space,
+            available_space,
+            total_space,
+            allocation_granularity: (sectors_per_cluster * bytes_per_sector) as u64,
+        })
+    }
+}